@@ -0,0 +1,143 @@
+/* Copyright (c) 2020-2021 Alibaba Cloud and Intel Corporation
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+//! A small conformance shim for `RatsTls`, modeled on BoringSSL's `bogo_shim`.
+//!
+//! It builds a `RatsTls` instance from a handful of command-line flags,
+//! negotiates a single connection, echoes one message over it once the
+//! negotiation succeeds, and exits with a distinct status code depending on
+//! whether that outcome (negotiation and echo together) matched
+//! `-expect-fail`. This lets CI drive the FFI wrapper against a
+//! reference peer without standing up a full deployment.
+
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::io::AsRawFd;
+
+use verdictd::rats_tls::RatsTls;
+
+const EXIT_OK: i32 = 0;
+const EXIT_UNEXPECTED_SUCCESS: i32 = 1;
+const EXIT_UNEXPECTED_FAILURE: i32 = 2;
+const EXIT_BAD_OPTIONS: i32 = 3;
+
+#[derive(Debug, Default)]
+struct Options {
+    server: bool,
+    port: u16,
+    mutual: bool,
+    tls_type: Option<String>,
+    crypto: Option<String>,
+    attester: Option<String>,
+    verifier: Option<String>,
+    expect_fail: bool,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+        let mut opts = Options::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-server" => opts.server = true,
+                "-mutual" => opts.mutual = true,
+                "-expect-fail" => opts.expect_fail = true,
+                "-port" => {
+                    let value = args.next().ok_or("-port requires a value")?;
+                    opts.port = value.parse().map_err(|_| "-port must be a u16")?;
+                }
+                "-tls-type" => {
+                    opts.tls_type = Some(args.next().ok_or("-tls-type requires a value")?)
+                }
+                "-crypto" => opts.crypto = Some(args.next().ok_or("-crypto requires a value")?),
+                "-attester" => {
+                    opts.attester = Some(args.next().ok_or("-attester requires a value")?)
+                }
+                "-verifier" => {
+                    opts.verifier = Some(args.next().ok_or("-verifier requires a value")?)
+                }
+                other => return Err(format!("unrecognized flag: {}", other)),
+            }
+        }
+        Ok(opts)
+    }
+}
+
+fn main() {
+    let opts = match Options::parse(std::env::args().skip(1)) {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("bogo_shim: {}", e);
+            std::process::exit(EXIT_BAD_OPTIONS);
+        }
+    };
+
+    let stream = if opts.server {
+        let listener = TcpListener::bind(("127.0.0.1", opts.port)).expect("bind failed");
+        let (stream, _) = listener.accept().expect("accept failed");
+        stream
+    } else {
+        TcpStream::connect(("127.0.0.1", opts.port)).expect("connect failed")
+    };
+
+    let negotiated = run(&opts, stream);
+
+    std::process::exit(match (negotiated, opts.expect_fail) {
+        (true, false) => EXIT_OK,
+        (false, true) => EXIT_OK,
+        (true, true) => EXIT_UNEXPECTED_SUCCESS,
+        (false, false) => EXIT_UNEXPECTED_FAILURE,
+    });
+}
+
+/// Builds the `RatsTls` instance, negotiates once and, on success, echoes a
+/// single message. Returns whether the negotiation and the echo both
+/// succeeded; a failed echo counts as a failed outcome.
+fn run(opts: &Options, stream: TcpStream) -> bool {
+    let rats_tls = match RatsTls::new(
+        opts.server,
+        0,
+        &opts.tls_type,
+        &opts.crypto,
+        &opts.attester,
+        &opts.verifier,
+        &None,
+        opts.mutual,
+    ) {
+        Ok(rats_tls) => rats_tls,
+        Err(e) => {
+            eprintln!("bogo_shim: RatsTls::new failed: {:?}", e);
+            return false;
+        }
+    };
+
+    if let Err(e) = rats_tls.negotiate(stream.as_raw_fd()) {
+        eprintln!("bogo_shim: negotiate failed: {:?}", e);
+        return false;
+    }
+
+    if opts.server {
+        let mut buf = [0u8; 64];
+        match rats_tls.receive(&mut buf) {
+            Ok(len) => {
+                if rats_tls.transmit(&buf[..len]).is_err() {
+                    return false;
+                }
+            }
+            Err(e) => {
+                eprintln!("bogo_shim: receive failed: {:?}", e);
+                return false;
+            }
+        }
+    } else {
+        let msg = b"bogo_shim ping";
+        if rats_tls.transmit(msg).is_err() {
+            return false;
+        }
+        let mut buf = [0u8; 64];
+        if rats_tls.receive(&mut buf).is_err() {
+            return false;
+        }
+    }
+
+    true
+}
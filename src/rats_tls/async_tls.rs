@@ -0,0 +1,103 @@
+/* Copyright (c) 2020-2021 Alibaba Cloud and Intel Corporation
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+use std::fmt;
+use std::os::unix::io::RawFd;
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+
+use super::{RatsTls, RatsTlsError};
+
+/// Errors produced by [`AsyncRatsTls`] on top of the errors `RatsTls` itself
+/// can return.
+#[derive(Debug)]
+pub enum AsyncRatsTlsError {
+    /// The underlying `RatsTls` call failed.
+    RatsTls(RatsTlsError),
+    /// The blocking task running the FFI call was cancelled or panicked.
+    JoinError(tokio::task::JoinError),
+}
+
+impl fmt::Display for AsyncRatsTlsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AsyncRatsTlsError::RatsTls(err) => write!(f, "rats_tls call failed: {}", err),
+            AsyncRatsTlsError::JoinError(err) => write!(f, "blocking task failed: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for AsyncRatsTlsError {}
+
+impl From<RatsTlsError> for AsyncRatsTlsError {
+    fn from(err: RatsTlsError) -> Self {
+        AsyncRatsTlsError::RatsTls(err)
+    }
+}
+
+/// An async wrapper around [`RatsTls`] that runs the blocking FFI calls on
+/// `tokio::task::spawn_blocking`, so a multiplexed async server doesn't stall
+/// its runtime while a handshake or IO call is in flight.
+///
+/// The handle is kept behind an `Arc` so cloning it for a blocking task only
+/// bumps a refcount rather than copying the owning pointer — `RatsTls`'s
+/// `Drop` calls `rats_tls_cleanup`, so two independent owners of the same
+/// pointer would double-clean it up. Only one blocking operation per handle
+/// may run at a time, guarded by an async mutex, since the underlying
+/// `rats_tls_handle` is not reentrant.
+#[derive(Clone)]
+pub struct AsyncRatsTls {
+    inner: Arc<Mutex<Arc<RatsTls>>>,
+}
+
+impl AsyncRatsTls {
+    pub fn new(rats_tls: RatsTls) -> AsyncRatsTls {
+        AsyncRatsTls {
+            inner: Arc::new(Mutex::new(Arc::new(rats_tls))),
+        }
+    }
+
+    pub async fn negotiate(&self, fd: RawFd) -> Result<(), AsyncRatsTlsError> {
+        let guard = Arc::clone(&self.inner).lock_owned().await;
+        let rats_tls = Arc::clone(&guard);
+        tokio::task::spawn_blocking(move || {
+            let _guard = guard;
+            rats_tls.negotiate(fd)
+        })
+            .await
+            .map_err(AsyncRatsTlsError::JoinError)?
+            .map_err(AsyncRatsTlsError::from)
+    }
+
+    pub async fn receive(&self, buf: &mut [u8]) -> Result<usize, AsyncRatsTlsError> {
+        let guard = Arc::clone(&self.inner).lock_owned().await;
+        let rats_tls = Arc::clone(&guard);
+        let mut owned = vec![0u8; buf.len()];
+        let (owned, res) = tokio::task::spawn_blocking(move || {
+            let _guard = guard;
+            let res = rats_tls.receive(&mut owned);
+            (owned, res)
+        })
+        .await
+        .map_err(AsyncRatsTlsError::JoinError)?;
+
+        let len = res.map_err(AsyncRatsTlsError::from)?;
+        buf[..len].copy_from_slice(&owned[..len]);
+        Ok(len)
+    }
+
+    pub async fn transmit(&self, buf: &[u8]) -> Result<usize, AsyncRatsTlsError> {
+        let guard = Arc::clone(&self.inner).lock_owned().await;
+        let rats_tls = Arc::clone(&guard);
+        let owned = buf.to_vec();
+        tokio::task::spawn_blocking(move || {
+            let _guard = guard;
+            rats_tls.transmit(&owned)
+        })
+            .await
+            .map_err(AsyncRatsTlsError::JoinError)?
+            .map_err(AsyncRatsTlsError::from)
+    }
+}
@@ -0,0 +1,121 @@
+/* Copyright (c) 2020-2021 Alibaba Cloud and Intel Corporation
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+use std::cell::{Cell, RefCell};
+use std::fmt;
+
+use super::ffi::rats_tls_err_t;
+
+thread_local! {
+    /// Stashes the OPA `parseInfo`/denial reason for the verification
+    /// callback currently running on this thread, so `negotiate` can turn a
+    /// policy rejection into a `RatsTlsError::PolicyDenied` instead of a
+    /// generic failure once the FFI call returns.
+    static LAST_POLICY_DENIAL: RefCell<Option<String>> = RefCell::new(None);
+
+    /// Set by the verification callback when the peer's evidence type has no
+    /// registered handler, so `negotiate` can turn the resulting FFI failure
+    /// into `RatsTlsError::NotImplemented` instead of a generic rejection.
+    static LAST_NOT_IMPLEMENTED: Cell<bool> = Cell::new(false);
+}
+
+pub(crate) fn clear_callback_state() {
+    LAST_POLICY_DENIAL.with(|cell| *cell.borrow_mut() = None);
+    LAST_NOT_IMPLEMENTED.with(|cell| cell.set(false));
+}
+
+pub(crate) fn stash_policy_denial(parse_info: String) {
+    LAST_POLICY_DENIAL.with(|cell| *cell.borrow_mut() = Some(parse_info));
+}
+
+pub(crate) fn take_policy_denial() -> Option<String> {
+    LAST_POLICY_DENIAL.with(|cell| cell.borrow_mut().take())
+}
+
+pub(crate) fn stash_not_implemented() {
+    LAST_NOT_IMPLEMENTED.with(|cell| cell.set(true));
+}
+
+pub(crate) fn take_not_implemented() -> bool {
+    LAST_NOT_IMPLEMENTED.with(|cell| cell.replace(false))
+}
+
+/// Structured errors surfaced by [`super::RatsTls`], replacing the opaque
+/// `rats_tls_err_t` codes returned directly by the FFI layer.
+#[derive(Debug)]
+pub enum RatsTlsError {
+    /// `rats_tls_init()` failed, e.g. due to an unsupported attester/verifier
+    /// combination or a malformed configuration.
+    InitFailed(rats_tls_err_t),
+    /// The peer rejected the handshake (or we rejected theirs) for a reason
+    /// other than an explicit policy decision, e.g. a protocol mismatch.
+    NegotiationRejected(rats_tls_err_t),
+    /// The verification callback ran and the configured OPA policy denied
+    /// the handshake; `parse_info` carries the policy engine's explanation.
+    PolicyDenied { parse_info: String },
+    /// The handshake completed but the transport was closed or reset.
+    Transport(rats_tls_err_t),
+    /// The peer presented an evidence type this build doesn't know how to
+    /// verify (e.g. a TEE family without a registered callback).
+    NotImplemented,
+    /// An FFI error code that doesn't map to any of the above.
+    Unknown(rats_tls_err_t),
+}
+
+impl fmt::Display for RatsTlsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RatsTlsError::InitFailed(err) => write!(f, "rats_tls_init() failed: {}", err),
+            RatsTlsError::NegotiationRejected(err) => {
+                write!(f, "negotiation rejected: {}", err)
+            }
+            RatsTlsError::PolicyDenied { parse_info } => {
+                write!(f, "handshake denied by policy: {}", parse_info)
+            }
+            RatsTlsError::Transport(err) => write!(f, "transport error: {}", err),
+            RatsTlsError::NotImplemented => {
+                write!(f, "evidence type not implemented")
+            }
+            RatsTlsError::Unknown(err) => write!(f, "unknown rats_tls error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for RatsTlsError {}
+
+impl From<rats_tls_err_t> for RatsTlsError {
+    /// Wraps a raw FFI error code that hasn't been classified into one of
+    /// the more specific variants.
+    fn from(err: rats_tls_err_t) -> Self {
+        RatsTlsError::Unknown(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_includes_the_policy_denial_reason() {
+        let err = RatsTlsError::PolicyDenied {
+            parse_info: "mrEnclave not in allow-list".to_string(),
+        };
+        assert_eq!(
+            err.to_string(),
+            "handshake denied by policy: mrEnclave not in allow-list"
+        );
+    }
+
+    #[test]
+    fn display_includes_the_raw_error_code() {
+        assert_eq!(
+            RatsTlsError::Transport(42).to_string(),
+            "transport error: 42"
+        );
+        assert_eq!(
+            RatsTlsError::NotImplemented.to_string(),
+            "evidence type not implemented"
+        );
+    }
+}
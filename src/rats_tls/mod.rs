@@ -13,6 +13,12 @@ use std::ptr::NonNull;
 mod ffi;
 use ffi::*;
 
+pub mod async_tls;
+pub use async_tls::{AsyncRatsTls, AsyncRatsTlsError};
+
+mod error;
+pub use error::RatsTlsError;
+
 pub struct RatsTlsRef(Opaque);
 
 unsafe impl ForeignTypeRef for RatsTlsRef {
@@ -68,6 +74,18 @@ impl DerefMut for RatsTls {
     }
 }
 
+fn parse_cert_algo(name: &str) -> Result<rats_tls_cert_algo_t, RatsTlsError> {
+    match name {
+        "ecdsa-p256" => Ok(RATS_TLS_CERT_ALGO_ECDSA_256),
+        "ecdsa-p384" => Ok(RATS_TLS_CERT_ALGO_ECDSA_384),
+        "rsa-3072" => Ok(RATS_TLS_CERT_ALGO_RSA_3072),
+        _ => {
+            error!("unknown cert algo '{}'", name);
+            Err(RatsTlsError::InitFailed(RATS_TLS_ERR_INVALID))
+        }
+    }
+}
+
 impl RatsTls {
     pub fn new(
         server: bool,
@@ -76,8 +94,9 @@ impl RatsTls {
         crypto: &Option<String>,
         attester: &Option<String>,
         verifier: &Option<String>,
+        cert_algo: &Option<String>,
         mutual: bool,
-    ) -> Result<RatsTls, rats_tls_err_t> {
+    ) -> Result<RatsTls, RatsTlsError> {
         let mut conf: rats_tls_conf_t = Default::default();
         conf.api_version = RATS_TLS_API_VERSION_DEFAULT;
         conf.log_level = RATS_TLS_LOG_LEVEL_DEBUG;
@@ -93,7 +112,10 @@ impl RatsTls {
         if let Some(verifier) = verifier {
             conf.verifier_type[..verifier.len()].copy_from_slice(verifier.as_bytes());
         }
-        conf.cert_algo = RATS_TLS_CERT_ALGO_DEFAULT;
+        conf.cert_algo = match cert_algo {
+            Some(cert_algo) => parse_cert_algo(cert_algo)?,
+            None => RATS_TLS_CERT_ALGO_DEFAULT,
+        };
         conf.enclave_id = enclave_id;
         if mutual {
             conf.flags |= RATS_TLS_CONF_FLAGS_MUTUAL;
@@ -107,27 +129,36 @@ impl RatsTls {
         let err = unsafe { rats_tls_init(&conf, &mut tls) };
         if err != RATS_TLS_ERR_NONE {
             error!("rats_tls_init() failed");
-            return Err(err);
+            return Err(RatsTlsError::InitFailed(err));
         }
 
         let err = unsafe { rats_tls_set_verification_callback(&mut tls, Some(Self::callback)) };
         if err == RATS_TLS_ERR_NONE {
             Ok(unsafe { RatsTls::from_ptr(tls) })
         } else {
-            Err(err)
+            Err(RatsTlsError::InitFailed(err))
         }
     }
 
-    pub fn negotiate(&self, fd: RawFd) -> Result<(), rats_tls_err_t> {
+    pub fn negotiate(&self, fd: RawFd) -> Result<(), RatsTlsError> {
+        // The verification callback runs synchronously on this thread during
+        // rats_tls_negotiate(); clear any state left over from a previous
+        // call on this thread (or handle) before it can run again.
+        error::clear_callback_state();
+
         let err = unsafe { rats_tls_negotiate(self.as_ptr(), fd) };
         if err == RATS_TLS_ERR_NONE {
             Ok(())
+        } else if error::take_not_implemented() {
+            Err(RatsTlsError::NotImplemented)
+        } else if let Some(parse_info) = error::take_policy_denial() {
+            Err(RatsTlsError::PolicyDenied { parse_info })
         } else {
-            Err(err)
+            Err(RatsTlsError::NegotiationRejected(err))
         }
     }
 
-    pub fn receive(&self, buf: &mut [u8]) -> Result<usize, rats_tls_err_t> {
+    pub fn receive(&self, buf: &mut [u8]) -> Result<usize, RatsTlsError> {
         let mut len: size_t = buf.len() as size_t;
         let err = unsafe {
             rats_tls_receive(
@@ -139,11 +170,11 @@ impl RatsTls {
         if err == RATS_TLS_ERR_NONE {
             Ok(len as usize)
         } else {
-            Err(err)
+            Err(RatsTlsError::Transport(err))
         }
     }
 
-    pub fn transmit(&self, buf: &[u8]) -> Result<usize, rats_tls_err_t> {
+    pub fn transmit(&self, buf: &[u8]) -> Result<usize, RatsTlsError> {
         let mut len: size_t = buf.len() as size_t;
         let err = unsafe {
             rats_tls_transmit(
@@ -155,21 +186,27 @@ impl RatsTls {
         if err == RATS_TLS_ERR_NONE {
             Ok(len as usize)
         } else {
-            Err(err)
+            Err(RatsTlsError::Transport(err))
         }
     }
 
-    fn sgx_callback(ev: rtls_sgx_evidence_t) -> Result<(), String> {
+    fn sgx_callback(ev: rtls_sgx_evidence_t, peer_cert: &[u8]) -> Result<(), String> {
         let mr_enclave =
             base64::encode(unsafe { std::slice::from_raw_parts(ev.mr_enclave, 32).to_vec() });
         let mr_signer =
             base64::encode(unsafe { std::slice::from_raw_parts(ev.mr_signer, 32).to_vec() });
+        let raw_quote = base64::encode(unsafe {
+            std::slice::from_raw_parts(ev.quote, ev.quote_size as usize).to_vec()
+        });
+        let peer_cert = base64::encode(peer_cert);
 
         let input = serde_json::json!({
             "mrEnclave": mr_enclave,
             "mrSigner": mr_signer,
             "productId": ev.product_id,
-            "svn": ev.security_version
+            "svn": ev.security_version,
+            "rawQuote": raw_quote,
+            "peerCert": peer_cert
         });
 
         policy_engine::opa::opa_engine::make_decision(resources::opa::OPA_POLICY_SGX, resources::opa::OPA_DATA_SGX, &input.to_string())
@@ -181,17 +218,27 @@ impl RatsTls {
                 if res["allow"] == true {
                     Ok(())
                 } else {
-                    error!("parseInfo: {}", res["parseInfo"].to_string());
+                    let parse_info = res["parseInfo"].to_string();
+                    error!("parseInfo: {}", parse_info);
+                    error::stash_policy_denial(parse_info);
                     Err("decision is false".to_string())
                 }
             })
     }
 
-    fn csv_callback(ev: rtls_csv_evidence_t) -> Result<(), String> {
+    fn csv_callback(ev: rtls_csv_evidence_t, peer_cert: &[u8]) -> Result<(), String> {
         let measure_b64 =
             base64::encode(unsafe { std::slice::from_raw_parts(ev.measure, 32).to_vec() });
+        let raw_quote = base64::encode(unsafe {
+            std::slice::from_raw_parts(ev.report, ev.report_size as usize).to_vec()
+        });
+        let peer_cert = base64::encode(peer_cert);
 
-        let input = serde_json::json!({ "measure": measure_b64 });
+        let input = serde_json::json!({
+            "measure": measure_b64,
+            "rawQuote": raw_quote,
+            "peerCert": peer_cert
+        });
 
         policy_engine::opa::opa_engine::make_decision(
             resources::opa::OPA_POLICY_CSV,
@@ -204,21 +251,117 @@ impl RatsTls {
             if res["allow"] == true {
                 Ok(())
             } else {
-                error!("parseInfo: {}", res["parseInfo"].to_string());
+                let parse_info = res["parseInfo"].to_string();
+                error!("parseInfo: {}", parse_info);
+                error::stash_policy_denial(parse_info);
+                Err("decision is false".to_string())
+            }
+        })
+    }
+
+    fn tdx_callback(ev: rtls_tdx_evidence_t, peer_cert: &[u8]) -> Result<(), String> {
+        let mr_td = base64::encode(unsafe { std::slice::from_raw_parts(ev.mr_td, 48).to_vec() });
+        let rtmrs = base64::encode(unsafe { std::slice::from_raw_parts(ev.rtmrs, 192).to_vec() });
+        let report_data =
+            base64::encode(unsafe { std::slice::from_raw_parts(ev.report_data, 64).to_vec() });
+        let raw_quote = base64::encode(unsafe {
+            std::slice::from_raw_parts(ev.quote, ev.quote_size as usize).to_vec()
+        });
+        let peer_cert = base64::encode(peer_cert);
+
+        let input = serde_json::json!({
+            "mrTd": mr_td,
+            "rtmrs": rtmrs,
+            "reportData": report_data,
+            "rawQuote": raw_quote,
+            "peerCert": peer_cert
+        });
+
+        policy_engine::opa::opa_engine::make_decision(
+            resources::opa::OPA_POLICY_TDX,
+            resources::opa::OPA_DATA_TDX,
+            &input.to_string(),
+        )
+        .map_err(|e| format!("make_decision error: {}", e))
+        .and_then(|res| serde_json::from_str(&res).map_err(|_| "Json unmashall failed".to_string()))
+        .and_then(|res: serde_json::Value| {
+            if res["allow"] == true {
+                Ok(())
+            } else {
+                let parse_info = res["parseInfo"].to_string();
+                error!("parseInfo: {}", parse_info);
+                error::stash_policy_denial(parse_info);
+                Err("decision is false".to_string())
+            }
+        })
+    }
+
+    fn snp_callback(ev: rtls_snp_evidence_t, peer_cert: &[u8]) -> Result<(), String> {
+        let measurement =
+            base64::encode(unsafe { std::slice::from_raw_parts(ev.measurement, 48).to_vec() });
+        let launch_digest =
+            base64::encode(unsafe { std::slice::from_raw_parts(ev.launch_digest, 48).to_vec() });
+        let raw_quote = base64::encode(unsafe {
+            std::slice::from_raw_parts(ev.report, ev.report_size as usize).to_vec()
+        });
+        let peer_cert = base64::encode(peer_cert);
+
+        let input = serde_json::json!({
+            "measurement": measurement,
+            "launchDigest": launch_digest,
+            "policy": ev.policy,
+            "rawQuote": raw_quote,
+            "peerCert": peer_cert
+        });
+
+        policy_engine::opa::opa_engine::make_decision(
+            resources::opa::OPA_POLICY_SNP,
+            resources::opa::OPA_DATA_SNP,
+            &input.to_string(),
+        )
+        .map_err(|e| format!("make_decision error: {}", e))
+        .and_then(|res| serde_json::from_str(&res).map_err(|_| "Json unmashall failed".to_string()))
+        .and_then(|res: serde_json::Value| {
+            if res["allow"] == true {
+                Ok(())
+            } else {
+                let parse_info = res["parseInfo"].to_string();
+                error!("parseInfo: {}", parse_info);
+                error::stash_policy_denial(parse_info);
                 Err("decision is false".to_string())
             }
         })
     }
 
+    /// Builds a slice over the peer's DER certificate, if the evidence
+    /// carries one. `cert` may be null (or `cert_len` zero) for evidence
+    /// types that don't embed a certificate, and `from_raw_parts` requires a
+    /// non-null, aligned pointer even for a zero-length slice.
+    fn peer_cert_slice(evidence: *mut rtls_evidence) -> &'static [u8] {
+        let cert = unsafe { (*evidence).cert };
+        let cert_len = unsafe { (*evidence).cert_len } as usize;
+        if cert.is_null() || cert_len == 0 {
+            &[]
+        } else {
+            unsafe { std::slice::from_raw_parts(cert, cert_len) }
+        }
+    }
+
     #[no_mangle]
     extern "C" fn callback(evidence: *mut ::std::os::raw::c_void) -> ::std::os::raw::c_int {
         info!("Verdictd Rats-TLS callback function is called.");
         let evidence = evidence as *mut rtls_evidence;
+        let peer_cert = Self::peer_cert_slice(evidence);
         let res = if unsafe { (*evidence).type_ } == enclave_evidence_type_t_SGX_ECDSA {
-            Self::sgx_callback(unsafe { (*evidence).__bindgen_anon_1.sgx })
+            Self::sgx_callback(unsafe { (*evidence).__bindgen_anon_1.sgx }, peer_cert)
         } else if unsafe { (*evidence).type_ } == enclave_evidence_type_t_CSV {
-            Self::csv_callback(unsafe { (*evidence).__bindgen_anon_1.csv })
+            Self::csv_callback(unsafe { (*evidence).__bindgen_anon_1.csv }, peer_cert)
+        } else if unsafe { (*evidence).type_ } == enclave_evidence_type_t_TDX_ECDSA {
+            Self::tdx_callback(unsafe { (*evidence).__bindgen_anon_1.tdx }, peer_cert)
+        } else if unsafe { (*evidence).type_ } == enclave_evidence_type_t_SNP {
+            Self::snp_callback(unsafe { (*evidence).__bindgen_anon_1.snp }, peer_cert)
         } else {
+            error::stash_not_implemented();
             Err("Not implemented".to_string())
         };
 
@@ -233,3 +376,29 @@ impl RatsTls {
         allow
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_cert_algo_maps_known_names() {
+        assert_eq!(
+            parse_cert_algo("ecdsa-p256").unwrap(),
+            RATS_TLS_CERT_ALGO_ECDSA_256
+        );
+        assert_eq!(
+            parse_cert_algo("ecdsa-p384").unwrap(),
+            RATS_TLS_CERT_ALGO_ECDSA_384
+        );
+        assert_eq!(
+            parse_cert_algo("rsa-3072").unwrap(),
+            RATS_TLS_CERT_ALGO_RSA_3072
+        );
+    }
+
+    #[test]
+    fn parse_cert_algo_rejects_unknown_name() {
+        assert!(parse_cert_algo("ecdsa-p521").is_err());
+    }
+}